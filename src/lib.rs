@@ -1,7 +1,11 @@
 use wasm_bindgen::prelude::*;
-use web_sys::{WebGlProgram, WebGlRenderingContext, WebGlShader};
-use nalgebra::{Matrix4, Rotation3, Vector3};
+use web_sys::{HtmlImageElement, PointerEvent, WebGl2RenderingContext, WebGlProgram, WebGlShader, WebGlTexture, WebGlVertexArrayObject, WheelEvent};
+use nalgebra::{Matrix3, Matrix4, Rotation3, Vector3};
+use bytemuck::{Pod, Zeroable};
+use std::cell::Cell;
 use std::f32::consts::PI;
+use std::mem::size_of;
+use std::rc::Rc;
 
 // A macro to provide `println!(..)`-style syntax for `console.log` logging.
 #[allow(unused_macros)]
@@ -15,36 +19,74 @@ macro_rules! log {
 const VERTEX_SHADER: &str = r#"
     attribute vec4 aVertexPosition;
     attribute vec4 aVertexColor;
-    
+    attribute vec2 aTextureCoord;
+    attribute vec3 aVertexNormal;
+
     uniform mat4 uModelViewMatrix;
     uniform mat4 uProjectionMatrix;
-    
+    uniform mat3 uNormalMatrix;
+
     varying lowp vec4 vColor;
-    
+    varying highp vec2 vTextureCoord;
+    varying highp vec3 vNormal;
+
     void main() {
         gl_Position = uProjectionMatrix * uModelViewMatrix * aVertexPosition;
         vColor = aVertexColor;
+        vTextureCoord = aTextureCoord;
+        vNormal = uNormalMatrix * aVertexNormal;
     }
 "#;
 
 // Fragment shader program
 const FRAGMENT_SHADER: &str = r#"
     varying lowp vec4 vColor;
-    
+    varying highp vec2 vTextureCoord;
+    varying highp vec3 vNormal;
+
+    uniform sampler2D uSampler;
+    uniform highp vec3 uLightDirection;
+
     void main() {
-        gl_FragColor = vColor;
+        highp vec3 ambient = vec3(0.2, 0.2, 0.2);
+        highp float diffuse = max(dot(normalize(vNormal), normalize(uLightDirection)), 0.0);
+        highp vec3 lighting = ambient + diffuse;
+
+        highp vec4 texelColor = texture2D(uSampler, vTextureCoord);
+        gl_FragColor = vec4(texelColor.rgb * vColor.rgb * lighting, texelColor.a * vColor.a);
     }
 "#;
 
 #[wasm_bindgen]
 pub struct Cube {
-    gl: WebGlRenderingContext,
+    gl: WebGl2RenderingContext,
     program_info: ProgramInfo,
     buffers: Buffers,
+    vao: WebGlVertexArrayObject,
+    texture: WebGlTexture,
+    light_direction: Vector3<f32>,
     rotation: f32,
+    auto_rotate: bool,
+    yaw: Rc<Cell<f32>>,
+    pitch: Rc<Cell<f32>>,
+    distance: Rc<Cell<f32>>,
     last_time: f64,
     animation_id: Option<i32>,
     animation_closure: Option<Closure<dyn FnMut(f64)>>,
+    pointerdown_closure: Option<Closure<dyn FnMut(PointerEvent)>>,
+    pointermove_closure: Option<Closure<dyn FnMut(PointerEvent)>>,
+    pointerup_closure: Option<Closure<dyn FnMut(PointerEvent)>>,
+    wheel_closure: Option<Closure<dyn FnMut(WheelEvent)>>,
+    mask_state: MaskState,
+    mask_stack: Vec<Vec<f32>>,
+}
+
+// Tracks whether the cube is currently clipped to a stencil mask, and if so,
+// the stencil reference value the next draw must match.
+#[derive(Clone, Copy, PartialEq)]
+enum MaskState {
+    NoMask,
+    Masked(u8),
 }
 
 struct ProgramInfo {
@@ -56,16 +98,31 @@ struct ProgramInfo {
 struct AttribLocations {
     vertex_position: u32,
     vertex_color: u32,
+    texture_coord: u32,
+    vertex_normal: u32,
 }
 
 struct UniformLocations {
     projection_matrix: Option<web_sys::WebGlUniformLocation>,
     model_view_matrix: Option<web_sys::WebGlUniformLocation>,
+    normal_matrix: Option<web_sys::WebGlUniformLocation>,
+    sampler: Option<web_sys::WebGlUniformLocation>,
+    light_direction: Option<web_sys::WebGlUniformLocation>,
+}
+
+// A single vertex's worth of per-vertex attributes, uploaded as one
+// interleaved buffer instead of four parallel ones.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct Vertex {
+    position: [f32; 3],
+    color: [f32; 4],
+    texture_coord: [f32; 2],
+    normal: [f32; 3],
 }
 
 struct Buffers {
-    position: web_sys::WebGlBuffer,
-    color: web_sys::WebGlBuffer,
+    vertices: web_sys::WebGlBuffer,
     indices: web_sys::WebGlBuffer,
 }
 
@@ -74,9 +131,9 @@ impl Cube {
     #[wasm_bindgen(constructor)]
     pub fn new(canvas_id: &str) -> Result<Cube, JsValue> {
         console_error_panic_hook::set_once();
-        
+
         web_sys::console::log_1(&"Cube::new() called".into());
-        
+
         // Get WebGL context
         let document = web_sys::window().unwrap().document().unwrap();
         let canvas = match document.get_element_by_id(canvas_id) {
@@ -86,9 +143,9 @@ impl Cube {
                 return Err(JsValue::from_str(&format!("Could not find canvas with id: {}", canvas_id)));
             }
         };
-        
+
         web_sys::console::log_1(&"Canvas element found".into());
-        
+
         let canvas: web_sys::HtmlCanvasElement = match canvas.dyn_into::<web_sys::HtmlCanvasElement>() {
             Ok(canvas) => canvas,
             Err(_) => {
@@ -96,89 +153,330 @@ impl Cube {
                 return Err(JsValue::from_str("Element is not a canvas"));
             }
         };
-        
-        let gl = match canvas.get_context("webgl") {
-            Ok(Some(ctx)) => match ctx.dyn_into::<WebGlRenderingContext>() {
+
+        // Request a stencil buffer so push_mask/pop_mask have somewhere to write.
+        let context_options = web_sys::WebGlContextAttributes::new();
+        context_options.set_stencil(true);
+
+        let gl = match canvas.get_context_with_context_options("webgl2", &context_options) {
+            Ok(Some(ctx)) => match ctx.dyn_into::<WebGl2RenderingContext>() {
                 Ok(gl) => {
-                    web_sys::console::log_1(&"WebGL context created successfully".into());
+                    web_sys::console::log_1(&"WebGL2 context created successfully".into());
                     gl
                 },
                 Err(_) => {
-                    web_sys::console::error_1(&"Failed to convert to WebGlRenderingContext".into());
-                    return Err(JsValue::from_str("Failed to convert to WebGlRenderingContext"));
+                    web_sys::console::error_1(&"Failed to convert to WebGl2RenderingContext".into());
+                    return Err(JsValue::from_str("Failed to convert to WebGl2RenderingContext"));
                 }
             },
             _ => {
-                web_sys::console::error_1(&"Failed to get WebGL context".into());
-                return Err(JsValue::from_str("Failed to get WebGL context"));
+                web_sys::console::error_1(&"Failed to get WebGL2 context".into());
+                return Err(JsValue::from_str("Failed to get WebGL2 context"));
             }
         };
-            
+
         // Initialize shaders and program
         let vert_shader = compile_shader(
             &gl,
-            WebGlRenderingContext::VERTEX_SHADER,
+            WebGl2RenderingContext::VERTEX_SHADER,
             VERTEX_SHADER,
         )?;
         let frag_shader = compile_shader(
             &gl,
-            WebGlRenderingContext::FRAGMENT_SHADER,
+            WebGl2RenderingContext::FRAGMENT_SHADER,
             FRAGMENT_SHADER,
         )?;
-        
+
         let program = link_program(&gl, &vert_shader, &frag_shader)?;
-        
+
         let program_info = ProgramInfo {
             program: program.clone(), // Clone the program to avoid moved value error
             attrib_locations: AttribLocations {
                 vertex_position: gl.get_attrib_location(&program, "aVertexPosition") as u32,
                 vertex_color: gl.get_attrib_location(&program, "aVertexColor") as u32,
+                texture_coord: gl.get_attrib_location(&program, "aTextureCoord") as u32,
+                vertex_normal: gl.get_attrib_location(&program, "aVertexNormal") as u32,
             },
             uniform_locations: UniformLocations {
                 projection_matrix: gl.get_uniform_location(&program, "uProjectionMatrix"),
                 model_view_matrix: gl.get_uniform_location(&program, "uModelViewMatrix"),
+                normal_matrix: gl.get_uniform_location(&program, "uNormalMatrix"),
+                sampler: gl.get_uniform_location(&program, "uSampler"),
+                light_direction: gl.get_uniform_location(&program, "uLightDirection"),
             },
         };
-        
+
         // Create buffers
         let buffers = init_buffers(&gl)?;
-        
+
+        // Build a VAO that records the attribute layout once, so `render` only
+        // has to bind it instead of repeating four vertex_attrib_pointer calls.
+        let vao = init_vertex_array(&gl, &program_info, &buffers)?;
+
+        // Create a placeholder texture until a real image is loaded
+        let texture = init_texture(&gl)?;
+
         // Set clear color and enable depth test
         gl.clear_color(0.0, 0.0, 0.0, 1.0);
         gl.clear_depth(1.0);
-        gl.enable(WebGlRenderingContext::DEPTH_TEST);
-        gl.depth_func(WebGlRenderingContext::LEQUAL);
-        
+        gl.clear_stencil(0);
+        gl.enable(WebGl2RenderingContext::DEPTH_TEST);
+        gl.depth_func(WebGl2RenderingContext::LEQUAL);
+
+        // Always on; render() switches between ALWAYS (unmasked) and EQUAL
+        // (masked) via stencil_func rather than toggling this off.
+        gl.enable(WebGl2RenderingContext::STENCIL_TEST);
+        gl.stencil_func(WebGl2RenderingContext::ALWAYS, 0, 0xff);
+
         let window = web_sys::window().unwrap();
         let performance = window.performance().unwrap();
-        
+
+        // Camera state is shared with the pointer/wheel closures below via
+        // Rc<Cell<_>> rather than a raw `*mut Cube`, since these closures are
+        // registered before the constructed Cube has a stable address.
+        let yaw = Rc::new(Cell::new(0.0f32));
+        let pitch = Rc::new(Cell::new(0.0f32));
+        let distance = Rc::new(Cell::new(6.0f32));
+        let dragging = Rc::new(Cell::new(false));
+        let last_pointer_pos = Rc::new(Cell::new((0.0f32, 0.0f32)));
+
+        let pointerdown_closure = {
+            let dragging = dragging.clone();
+            let last_pointer_pos = last_pointer_pos.clone();
+            Closure::wrap(Box::new(move |event: PointerEvent| {
+                dragging.set(true);
+                last_pointer_pos.set((event.client_x() as f32, event.client_y() as f32));
+            }) as Box<dyn FnMut(PointerEvent)>)
+        };
+
+        let pointermove_closure = {
+            let dragging = dragging.clone();
+            let last_pointer_pos = last_pointer_pos.clone();
+            let yaw = yaw.clone();
+            let pitch = pitch.clone();
+            Closure::wrap(Box::new(move |event: PointerEvent| {
+                if !dragging.get() {
+                    return;
+                }
+                const DRAG_SENSITIVITY: f32 = 0.01;
+                let (last_x, last_y) = last_pointer_pos.get();
+                let (x, y) = (event.client_x() as f32, event.client_y() as f32);
+                yaw.set(yaw.get() + (x - last_x) * DRAG_SENSITIVITY);
+                let new_pitch = (pitch.get() + (y - last_y) * DRAG_SENSITIVITY)
+                    .clamp(-PI / 2.0 + 0.01, PI / 2.0 - 0.01);
+                pitch.set(new_pitch);
+                last_pointer_pos.set((x, y));
+            }) as Box<dyn FnMut(PointerEvent)>)
+        };
+
+        let pointerup_closure = {
+            let dragging = dragging.clone();
+            Closure::wrap(Box::new(move |_event: PointerEvent| {
+                dragging.set(false);
+            }) as Box<dyn FnMut(PointerEvent)>)
+        };
+
+        let wheel_closure = {
+            let distance = distance.clone();
+            Closure::wrap(Box::new(move |event: WheelEvent| {
+                event.prevent_default();
+                const ZOOM_SENSITIVITY: f32 = 0.01;
+                let new_distance = (distance.get() + (event.delta_y() as f32) * ZOOM_SENSITIVITY)
+                    .clamp(2.0, 20.0);
+                distance.set(new_distance);
+            }) as Box<dyn FnMut(WheelEvent)>)
+        };
+
+        canvas.add_event_listener_with_callback("pointerdown", pointerdown_closure.as_ref().unchecked_ref())?;
+        canvas.add_event_listener_with_callback("pointermove", pointermove_closure.as_ref().unchecked_ref())?;
+        canvas.add_event_listener_with_callback("pointerup", pointerup_closure.as_ref().unchecked_ref())?;
+        canvas.add_event_listener_with_callback("wheel", wheel_closure.as_ref().unchecked_ref())?;
+
         Ok(Cube {
             gl,
             program_info,
             buffers,
+            vao,
+            texture,
+            light_direction: Vector3::new(0.5, 0.7, 1.0),
             rotation: 0.0,
+            auto_rotate: true,
+            yaw,
+            pitch,
+            distance,
             last_time: performance.now(),
             animation_id: None,
             animation_closure: None,
+            pointerdown_closure: Some(pointerdown_closure),
+            pointermove_closure: Some(pointermove_closure),
+            pointerup_closure: Some(pointerup_closure),
+            wheel_closure: Some(wheel_closure),
+            mask_state: MaskState::NoMask,
+            mask_stack: Vec::new(),
         })
     }
-    
+
+    // Kicks off an asynchronous load of `url` into the cube's texture. The
+    // placeholder pixel set in `new` remains bound until the image arrives.
+    pub fn load_texture(&mut self, url: &str) -> Result<(), JsValue> {
+        let image = HtmlImageElement::new()?;
+        let gl = self.gl.clone();
+        let texture = self.texture.clone();
+
+        let image_for_closure = image.clone();
+        let onload = Closure::wrap(Box::new(move || {
+            gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+            if let Err(err) = gl.tex_image_2d_with_u32_and_u32_and_html_image_element(
+                WebGl2RenderingContext::TEXTURE_2D,
+                0,
+                WebGl2RenderingContext::RGBA as i32,
+                WebGl2RenderingContext::RGBA,
+                WebGl2RenderingContext::UNSIGNED_BYTE,
+                &image_for_closure,
+            ) {
+                web_sys::console::error_1(&err);
+                return;
+            }
+
+            if is_power_of_2(image_for_closure.width()) && is_power_of_2(image_for_closure.height()) {
+                gl.generate_mipmap(WebGl2RenderingContext::TEXTURE_2D);
+            } else {
+                gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_WRAP_S, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+                gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_WRAP_T, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+                gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MIN_FILTER, WebGl2RenderingContext::LINEAR as i32);
+            }
+        }) as Box<dyn FnMut()>);
+
+        image.set_onload(Some(onload.as_ref().unchecked_ref()));
+        onload.forget();
+        image.set_src(url);
+
+        Ok(())
+    }
+
+    // Sets the direction the shader's single directional light shines from.
+    pub fn set_light_direction(&mut self, x: f32, y: f32, z: f32) {
+        self.light_direction = Vector3::new(x, y, z);
+    }
+
+    // Toggles the automatic spin layered on top of the pointer-driven orbit.
+    pub fn set_auto_rotate(&mut self, enabled: bool) {
+        self.auto_rotate = enabled;
+    }
+
+    // Clips subsequent draws to the triangles in `verts` (clip-space xyz
+    // triples), on top of any mask already pushed. Stacks with nested calls:
+    // a nested push only advances the stencil value where the parent mask's
+    // value is already present (`EQUAL` gating below), so only the true
+    // intersection of all pushed shapes passes until the matching `pop_mask`.
+    pub fn push_mask(&mut self, verts: &[f32]) -> Result<(), JsValue> {
+        // Gate the stamp on the parent mask's ref (0, i.e. the cleared
+        // stencil buffer, if there isn't one) so a nested mask can only ever
+        // narrow the area that's already passing, never widen it. INCR bumps
+        // whatever's already stored rather than overwriting it with the test
+        // value, so this also works at the top level (0 -> 1).
+        let parent_ref = self.mask_stack.len() as u8;
+        let new_ref = parent_ref + 1;
+        self.draw_mask_triangles(verts, parent_ref as i32, WebGl2RenderingContext::INCR)?;
+        self.mask_stack.push(verts.to_vec());
+        self.mask_state = MaskState::Masked(new_ref);
+        Ok(())
+    }
+
+    // Removes the mask most recently pushed with `push_mask`, restoring
+    // whatever mask (or lack of one) was in effect before it.
+    pub fn pop_mask(&mut self) -> Result<(), JsValue> {
+        let Some(verts) = self.mask_stack.pop() else {
+            return Ok(());
+        };
+        let parent_ref = self.mask_stack.len() as u8;
+        let child_ref = parent_ref + 1;
+        // Gated on this mask's own ref value, so only the pixels it actually
+        // stamped are touched; pixels outside it (still at the parent's ref,
+        // or 0) are left exactly as the parent mask left them.
+        self.draw_mask_triangles(&verts, child_ref as i32, WebGl2RenderingContext::DECR)?;
+        self.mask_state = if parent_ref == 0 {
+            MaskState::NoMask
+        } else {
+            MaskState::Masked(parent_ref)
+        };
+        Ok(())
+    }
+
+    // Shared by push_mask/pop_mask: draws `verts` with color and depth writes
+    // disabled, advancing the stencil buffer via `pass_op` (INCR to stamp a
+    // mask in, DECR to remove one) wherever it already holds `test_ref`
+    // (`EQUAL` gating is what makes nested masks intersect rather than
+    // union). Uses its own position-only buffer rather than the cube's VAO,
+    // since mask shapes are caller-defined and unrelated to the cube's
+    // vertex layout.
+    fn draw_mask_triangles(&self, verts: &[f32], test_ref: i32, pass_op: u32) -> Result<(), JsValue> {
+        let gl = &self.gl;
+
+        let mask_buffer = gl.create_buffer().ok_or("Failed to create mask buffer")?;
+        gl.bind_vertex_array(None);
+        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&mask_buffer));
+        gl.buffer_data_with_u8_array(
+            WebGl2RenderingContext::ARRAY_BUFFER,
+            bytemuck::cast_slice(verts),
+            WebGl2RenderingContext::STATIC_DRAW,
+        );
+
+        gl.use_program(Some(&self.program_info.program));
+        let vertex_position = self.program_info.attrib_locations.vertex_position;
+        gl.vertex_attrib_pointer_with_i32(vertex_position, 3, WebGl2RenderingContext::FLOAT, false, 0, 0);
+        gl.enable_vertex_attrib_array(vertex_position);
+
+        // Mask verts are already in clip space, so the vertex shader's
+        // transforms are left as identity for this draw.
+        gl.uniform_matrix4fv_with_f32_array(
+            self.program_info.uniform_locations.projection_matrix.as_ref(),
+            false,
+            Matrix4::<f32>::identity().as_slice(),
+        );
+        gl.uniform_matrix4fv_with_f32_array(
+            self.program_info.uniform_locations.model_view_matrix.as_ref(),
+            false,
+            Matrix4::<f32>::identity().as_slice(),
+        );
+        gl.uniform_matrix3fv_with_f32_array(
+            self.program_info.uniform_locations.normal_matrix.as_ref(),
+            false,
+            Matrix3::<f32>::identity().as_slice(),
+        );
+
+        gl.color_mask(false, false, false, false);
+        gl.depth_mask(false);
+        gl.stencil_func(WebGl2RenderingContext::EQUAL, test_ref, 0xff);
+        gl.stencil_op(WebGl2RenderingContext::KEEP, WebGl2RenderingContext::KEEP, pass_op);
+
+        let vertex_count = (verts.len() / 3) as i32;
+        gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, vertex_count);
+
+        gl.color_mask(true, true, true, true);
+        gl.depth_mask(true);
+        gl.disable_vertex_attrib_array(vertex_position);
+        gl.delete_buffer(Some(&mask_buffer));
+
+        Ok(())
+    }
+
     pub fn start(&mut self) -> Result<(), JsValue> {
         // If already running, do nothing
         if self.animation_id.is_some() {
             web_sys::console::log_1(&"Animation already running, ignoring start request".into());
             return Ok(());
         }
-        
+
         web_sys::console::log_1(&"Starting animation...".into());
-        
+
         let cube_ptr = self as *mut Cube;
-        
+
         // Create a new animation closure
         let animation_closure = Closure::wrap(Box::new(move |time: f64| {
             let cube = unsafe { &mut *cube_ptr };
             cube.render(time);
-            
+
             // Request next frame
             let window = web_sys::window().unwrap();
             if let Some(closure) = &cube.animation_closure {
@@ -188,7 +486,7 @@ impl Cube {
                 web_sys::console::warn_1(&"Animation closure is None in the render loop".into());
             }
         }) as Box<dyn FnMut(f64)>);
-        
+
         // Start the animation
         web_sys::console::log_1(&"Requesting first animation frame".into());
         let window = web_sys::window().unwrap();
@@ -205,7 +503,7 @@ impl Cube {
             }
         }
     }
-    
+
     pub fn stop(&mut self) {
         if let Some(id) = self.animation_id {
             let window = web_sys::window().unwrap();
@@ -214,121 +512,127 @@ impl Cube {
             self.animation_closure = None;
         }
     }
-    
+
     pub fn render(&mut self, time: f64) {
         let delta = time - self.last_time;
         self.last_time = time;
-        
-        // Update rotation
-        self.rotation += (delta as f32) * 0.001;
-        
+
+        // Update the auto-spin accumulator, which layers on top of the
+        // pointer-driven yaw when auto-rotate is enabled.
+        if self.auto_rotate {
+            self.rotation += (delta as f32) * 0.001;
+        }
+
         // Clear the canvas
-        self.gl.clear(WebGlRenderingContext::COLOR_BUFFER_BIT | WebGlRenderingContext::DEPTH_BUFFER_BIT);
-        
+        self.gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::DEPTH_BUFFER_BIT);
+
         // Create projection matrix
         let aspect = 1.0;
         let field_of_view = 45.0 * PI / 180.0;
         let z_near = 0.1;
         let z_far = 100.0;
-        
+
         let projection_matrix = Matrix4::new_perspective(aspect, field_of_view, z_near, z_far);
-        
-        // Create model view matrix
+
+        // Create model view matrix from the orbit camera: distance from the
+        // origin plus a yaw/pitch orientation driven by pointer drags (with
+        // the auto-spin accumulator layered onto yaw).
         let mut model_view_matrix = Matrix4::identity();
-        
-        // Translate the cube
-        model_view_matrix = model_view_matrix * Matrix4::new_translation(&Vector3::new(0.0, 0.0, -6.0));
-        
-        // Rotate the cube
-        let rotation = Rotation3::from_euler_angles(self.rotation, self.rotation, self.rotation);
+
+        model_view_matrix = model_view_matrix * Matrix4::new_translation(&Vector3::new(0.0, 0.0, -self.distance.get()));
+
+        let rotation = Rotation3::from_euler_angles(self.pitch.get(), self.yaw.get() + self.rotation, 0.0);
         model_view_matrix = model_view_matrix * rotation.to_homogeneous();
-        
-        // Draw the cube
-        {
-            // Positions
-            let num_components = 3;
-            let normalized = false;
-            let stride = 0;
-            let offset = 0;
-            
-            self.gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.buffers.position));
-            self.gl.vertex_attrib_pointer_with_i32(
-                self.program_info.attrib_locations.vertex_position,
-                num_components,
-                WebGlRenderingContext::FLOAT,
-                normalized,
-                stride,
-                offset,
-            );
-            self.gl.enable_vertex_attrib_array(self.program_info.attrib_locations.vertex_position);
-        }
-        
-        // Colors
-        {
-            let num_components = 4;
-            let normalized = false;
-            let stride = 0;
-            let offset = 0;
-            
-            self.gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.buffers.color));
-            self.gl.vertex_attrib_pointer_with_i32(
-                self.program_info.attrib_locations.vertex_color,
-                num_components,
-                WebGlRenderingContext::FLOAT,
-                normalized,
-                stride,
-                offset,
-            );
-            self.gl.enable_vertex_attrib_array(self.program_info.attrib_locations.vertex_color);
-        }
-        
-        // Indices
-        self.gl.bind_buffer(WebGlRenderingContext::ELEMENT_ARRAY_BUFFER, Some(&self.buffers.indices));
-        
+
+        // The normal matrix corrects surface normals for non-uniform scaling in
+        // the model-view transform: transpose(inverse(upper-left 3x3)).
+        let inverse_transpose = model_view_matrix
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity)
+            .transpose();
+        let normal_matrix = upper_left_3x3(&inverse_transpose);
+
+        // The VAO already remembers the buffer bindings and attrib pointers
+        // set up in `new`, so per-frame setup collapses to one bind.
+        self.gl.bind_vertex_array(Some(&self.vao));
+
         // Use the shader program
         self.gl.use_program(Some(&self.program_info.program));
-        
+
         // Set uniforms
         self.gl.uniform_matrix4fv_with_f32_array(
             self.program_info.uniform_locations.projection_matrix.as_ref(),
             false,
             projection_matrix.as_slice(),
         );
-        
+
         self.gl.uniform_matrix4fv_with_f32_array(
             self.program_info.uniform_locations.model_view_matrix.as_ref(),
             false,
             model_view_matrix.as_slice(),
         );
-        
+
+        self.gl.uniform_matrix3fv_with_f32_array(
+            self.program_info.uniform_locations.normal_matrix.as_ref(),
+            false,
+            normal_matrix.as_slice(),
+        );
+
+        self.gl.uniform3f(
+            self.program_info.uniform_locations.light_direction.as_ref(),
+            self.light_direction.x,
+            self.light_direction.y,
+            self.light_direction.z,
+        );
+
+        // Bind the texture to texture unit 0 and point uSampler at it
+        self.gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+        self.gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&self.texture));
+        self.gl.uniform1i(self.program_info.uniform_locations.sampler.as_ref(), 0);
+
+        // Restrict the draw to whatever region push_mask last staked out, if any.
+        match self.mask_state {
+            MaskState::NoMask => self.gl.stencil_func(WebGl2RenderingContext::ALWAYS, 0, 0xff),
+            MaskState::Masked(mask_ref) => {
+                self.gl.stencil_func(WebGl2RenderingContext::EQUAL, mask_ref as i32, 0xff)
+            }
+        }
+        self.gl.stencil_op(
+            WebGl2RenderingContext::KEEP,
+            WebGl2RenderingContext::KEEP,
+            WebGl2RenderingContext::KEEP,
+        );
+
         // Draw elements
         let vertex_count = 36;
-        let type_ = WebGlRenderingContext::UNSIGNED_SHORT;
+        let type_ = WebGl2RenderingContext::UNSIGNED_SHORT;
         let offset = 0;
         self.gl.draw_elements_with_i32(
-            WebGlRenderingContext::TRIANGLES,
+            WebGl2RenderingContext::TRIANGLES,
             vertex_count,
             type_,
             offset,
         );
+
+        self.gl.bind_vertex_array(None);
     }
 }
 
 // Helper function to compile a shader
 fn compile_shader(
-    gl: &WebGlRenderingContext,
+    gl: &WebGl2RenderingContext,
     shader_type: u32,
     source: &str,
 ) -> Result<WebGlShader, String> {
     let shader = gl
         .create_shader(shader_type)
         .ok_or_else(|| String::from("Unable to create shader object"))?;
-    
+
     gl.shader_source(&shader, source);
     gl.compile_shader(&shader);
-    
+
     if gl
-        .get_shader_parameter(&shader, WebGlRenderingContext::COMPILE_STATUS)
+        .get_shader_parameter(&shader, WebGl2RenderingContext::COMPILE_STATUS)
         .as_bool()
         .unwrap_or(false)
     {
@@ -342,20 +646,20 @@ fn compile_shader(
 
 // Helper function to link a shader program
 fn link_program(
-    gl: &WebGlRenderingContext,
+    gl: &WebGl2RenderingContext,
     vert_shader: &WebGlShader,
     frag_shader: &WebGlShader,
 ) -> Result<WebGlProgram, String> {
     let program = gl
         .create_program()
         .ok_or_else(|| String::from("Unable to create shader program"))?;
-    
+
     gl.attach_shader(&program, vert_shader);
     gl.attach_shader(&program, frag_shader);
     gl.link_program(&program);
-    
+
     if gl
-        .get_program_parameter(&program, WebGlRenderingContext::LINK_STATUS)
+        .get_program_parameter(&program, WebGl2RenderingContext::LINK_STATUS)
         .as_bool()
         .unwrap_or(false)
     {
@@ -367,8 +671,114 @@ fn link_program(
     }
 }
 
+// Helper function to create the placeholder texture used until an image loads
+fn init_texture(gl: &WebGl2RenderingContext) -> Result<WebGlTexture, JsValue> {
+    let texture = gl.create_texture().ok_or("Failed to create texture")?;
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+
+    // A single opaque white pixel, used until `load_texture` finishes, so the
+    // untextured cube shows its plain per-face vertex colors instead of a tint.
+    let level = 0;
+    let internal_format = WebGl2RenderingContext::RGBA as i32;
+    let width = 1;
+    let height = 1;
+    let border = 0;
+    let src_format = WebGl2RenderingContext::RGBA;
+    let src_type = WebGl2RenderingContext::UNSIGNED_BYTE;
+    let pixel: [u8; 4] = [255, 255, 255, 255];
+
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        WebGl2RenderingContext::TEXTURE_2D,
+        level,
+        internal_format,
+        width,
+        height,
+        border,
+        src_format,
+        src_type,
+        Some(&pixel),
+    )?;
+
+    Ok(texture)
+}
+
+// Extracts the upper-left 3x3 block of a 4x4 matrix, used to turn a model-view
+// matrix into a normal matrix.
+fn upper_left_3x3(m: &Matrix4<f32>) -> Matrix3<f32> {
+    Matrix3::new(
+        m[(0, 0)], m[(0, 1)], m[(0, 2)],
+        m[(1, 0)], m[(1, 1)], m[(1, 2)],
+        m[(2, 0)], m[(2, 1)], m[(2, 2)],
+    )
+}
+
+// Returns true if `value` is a power of two (used to decide whether mipmaps can be generated).
+fn is_power_of_2(value: u32) -> bool {
+    value != 0 && (value & (value - 1)) == 0
+}
+
+// Builds the VAO that records every vertex attrib pointer once, so `render`
+// no longer has to reconfigure them on every frame.
+fn init_vertex_array(
+    gl: &WebGl2RenderingContext,
+    program_info: &ProgramInfo,
+    buffers: &Buffers,
+) -> Result<WebGlVertexArrayObject, JsValue> {
+    let vao = gl.create_vertex_array().ok_or("Failed to create vertex array object")?;
+    gl.bind_vertex_array(Some(&vao));
+
+    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&buffers.vertices));
+    let stride = size_of::<Vertex>() as i32;
+
+    gl.vertex_attrib_pointer_with_i32(
+        program_info.attrib_locations.vertex_position,
+        3,
+        WebGl2RenderingContext::FLOAT,
+        false,
+        stride,
+        0,
+    );
+    gl.enable_vertex_attrib_array(program_info.attrib_locations.vertex_position);
+
+    gl.vertex_attrib_pointer_with_i32(
+        program_info.attrib_locations.vertex_color,
+        4,
+        WebGl2RenderingContext::FLOAT,
+        false,
+        stride,
+        12,
+    );
+    gl.enable_vertex_attrib_array(program_info.attrib_locations.vertex_color);
+
+    gl.vertex_attrib_pointer_with_i32(
+        program_info.attrib_locations.texture_coord,
+        2,
+        WebGl2RenderingContext::FLOAT,
+        false,
+        stride,
+        28,
+    );
+    gl.enable_vertex_attrib_array(program_info.attrib_locations.texture_coord);
+
+    gl.vertex_attrib_pointer_with_i32(
+        program_info.attrib_locations.vertex_normal,
+        3,
+        WebGl2RenderingContext::FLOAT,
+        false,
+        stride,
+        36,
+    );
+    gl.enable_vertex_attrib_array(program_info.attrib_locations.vertex_normal);
+
+    gl.bind_buffer(WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER, Some(&buffers.indices));
+
+    gl.bind_vertex_array(None);
+
+    Ok(vao)
+}
+
 // Helper function to initialize buffers
-fn init_buffers(gl: &WebGlRenderingContext) -> Result<Buffers, JsValue> {
+fn init_buffers(gl: &WebGl2RenderingContext) -> Result<Buffers, JsValue> {
     // Define vertices for a cube
     let positions = [
         // Front face
@@ -376,38 +786,38 @@ fn init_buffers(gl: &WebGlRenderingContext) -> Result<Buffers, JsValue> {
          1.0, -1.0,  1.0,
          1.0,  1.0,  1.0,
         -1.0,  1.0,  1.0,
-        
+
         // Back face
         -1.0, -1.0, -1.0,
         -1.0,  1.0, -1.0,
          1.0,  1.0, -1.0,
          1.0, -1.0, -1.0,
-        
+
         // Top face
         -1.0,  1.0, -1.0,
         -1.0,  1.0,  1.0,
          1.0,  1.0,  1.0,
          1.0,  1.0, -1.0,
-        
+
         // Bottom face
         -1.0, -1.0, -1.0,
          1.0, -1.0, -1.0,
          1.0, -1.0,  1.0,
         -1.0, -1.0,  1.0,
-        
+
         // Right face
          1.0, -1.0, -1.0,
          1.0,  1.0, -1.0,
          1.0,  1.0,  1.0,
          1.0, -1.0,  1.0,
-        
+
         // Left face
         -1.0, -1.0, -1.0,
         -1.0, -1.0,  1.0,
         -1.0,  1.0,  1.0,
         -1.0,  1.0, -1.0,
     ];
-    
+
     // Define colors for each face
     let colors = [
         // Front face: white
@@ -415,38 +825,116 @@ fn init_buffers(gl: &WebGlRenderingContext) -> Result<Buffers, JsValue> {
         1.0, 1.0, 1.0, 1.0,
         1.0, 1.0, 1.0, 1.0,
         1.0, 1.0, 1.0, 1.0,
-        
+
         // Back face: red
         1.0, 0.0, 0.0, 1.0,
         1.0, 0.0, 0.0, 1.0,
         1.0, 0.0, 0.0, 1.0,
         1.0, 0.0, 0.0, 1.0,
-        
+
         // Top face: green
         0.0, 1.0, 0.0, 1.0,
         0.0, 1.0, 0.0, 1.0,
         0.0, 1.0, 0.0, 1.0,
         0.0, 1.0, 0.0, 1.0,
-        
+
         // Bottom face: blue
         0.0, 0.0, 1.0, 1.0,
         0.0, 0.0, 1.0, 1.0,
         0.0, 0.0, 1.0, 1.0,
         0.0, 0.0, 1.0, 1.0,
-        
+
         // Right face: yellow
         1.0, 1.0, 0.0, 1.0,
         1.0, 1.0, 0.0, 1.0,
         1.0, 1.0, 0.0, 1.0,
         1.0, 1.0, 0.0, 1.0,
-        
+
         // Left face: purple
         1.0, 0.0, 1.0, 1.0,
         1.0, 0.0, 1.0, 1.0,
         1.0, 0.0, 1.0, 1.0,
         1.0, 0.0, 1.0, 1.0,
     ];
-    
+
+    // Define texture coordinates for each face (same layout on every face)
+    let texture_coords = [
+        // Front face
+        0.0, 0.0,
+        1.0, 0.0,
+        1.0, 1.0,
+        0.0, 1.0,
+
+        // Back face
+        0.0, 0.0,
+        1.0, 0.0,
+        1.0, 1.0,
+        0.0, 1.0,
+
+        // Top face
+        0.0, 0.0,
+        1.0, 0.0,
+        1.0, 1.0,
+        0.0, 1.0,
+
+        // Bottom face
+        0.0, 0.0,
+        1.0, 0.0,
+        1.0, 1.0,
+        0.0, 1.0,
+
+        // Right face
+        0.0, 0.0,
+        1.0, 0.0,
+        1.0, 1.0,
+        0.0, 1.0,
+
+        // Left face
+        0.0, 0.0,
+        1.0, 0.0,
+        1.0, 1.0,
+        0.0, 1.0,
+    ];
+
+    // Define the outward-facing normal for each face, replicated across its four vertices
+    let normals = [
+        // Front face
+        0.0,  0.0,  1.0,
+        0.0,  0.0,  1.0,
+        0.0,  0.0,  1.0,
+        0.0,  0.0,  1.0,
+
+        // Back face
+        0.0,  0.0, -1.0,
+        0.0,  0.0, -1.0,
+        0.0,  0.0, -1.0,
+        0.0,  0.0, -1.0,
+
+        // Top face
+        0.0,  1.0,  0.0,
+        0.0,  1.0,  0.0,
+        0.0,  1.0,  0.0,
+        0.0,  1.0,  0.0,
+
+        // Bottom face
+        0.0, -1.0,  0.0,
+        0.0, -1.0,  0.0,
+        0.0, -1.0,  0.0,
+        0.0, -1.0,  0.0,
+
+        // Right face
+        1.0,  0.0,  0.0,
+        1.0,  0.0,  0.0,
+        1.0,  0.0,  0.0,
+        1.0,  0.0,  0.0,
+
+        // Left face
+        -1.0,  0.0,  0.0,
+        -1.0,  0.0,  0.0,
+        -1.0,  0.0,  0.0,
+        -1.0,  0.0,  0.0,
+    ];
+
     // Define indices to draw the cube
     let indices = [
         0,  1,  2,    0,  2,  3,  // front
@@ -456,52 +944,42 @@ fn init_buffers(gl: &WebGlRenderingContext) -> Result<Buffers, JsValue> {
         16, 17, 18,   16, 18, 19, // right
         20, 21, 22,   20, 22, 23, // left
     ];
-    
-    // Create and bind position buffer
-    let position_buffer = gl.create_buffer().ok_or("Failed to create position buffer")?;
-    gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&position_buffer));
-    
-    // Pass the vertex positions to WebGL
-    unsafe {
-        let positions_array_buf_view = js_sys::Float32Array::view(&positions);
-        gl.buffer_data_with_array_buffer_view(
-            WebGlRenderingContext::ARRAY_BUFFER,
-            &positions_array_buf_view,
-            WebGlRenderingContext::STATIC_DRAW,
-        );
-    }
-    
-    // Create and bind color buffer
-    let color_buffer = gl.create_buffer().ok_or("Failed to create color buffer")?;
-    gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&color_buffer));
-    
-    // Pass the colors to WebGL
-    unsafe {
-        let colors_array_buf_view = js_sys::Float32Array::view(&colors);
-        gl.buffer_data_with_array_buffer_view(
-            WebGlRenderingContext::ARRAY_BUFFER,
-            &colors_array_buf_view,
-            WebGlRenderingContext::STATIC_DRAW,
-        );
-    }
-    
+
+    // Interleave the per-vertex attribute arrays above into a single Vec<Vertex>.
+    let verts: Vec<Vertex> = (0..24)
+        .map(|i| Vertex {
+            position: [positions[i * 3], positions[i * 3 + 1], positions[i * 3 + 2]],
+            color: [colors[i * 4], colors[i * 4 + 1], colors[i * 4 + 2], colors[i * 4 + 3]],
+            texture_coord: [texture_coords[i * 2], texture_coords[i * 2 + 1]],
+            normal: [normals[i * 3], normals[i * 3 + 1], normals[i * 3 + 2]],
+        })
+        .collect();
+
+    // Create and bind the interleaved vertex buffer
+    let vertex_buffer = gl.create_buffer().ok_or("Failed to create vertex buffer")?;
+    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&vertex_buffer));
+    gl.buffer_data_with_u8_array(
+        WebGl2RenderingContext::ARRAY_BUFFER,
+        bytemuck::cast_slice(&verts),
+        WebGl2RenderingContext::STATIC_DRAW,
+    );
+
     // Create and bind index buffer
     let index_buffer = gl.create_buffer().ok_or("Failed to create index buffer")?;
-    gl.bind_buffer(WebGlRenderingContext::ELEMENT_ARRAY_BUFFER, Some(&index_buffer));
-    
+    gl.bind_buffer(WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER, Some(&index_buffer));
+
     // Pass the indices to WebGL
     unsafe {
         let indices_array_buf_view = js_sys::Uint16Array::view(&indices);
         gl.buffer_data_with_array_buffer_view(
-            WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
+            WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
             &indices_array_buf_view,
-            WebGlRenderingContext::STATIC_DRAW,
+            WebGl2RenderingContext::STATIC_DRAW,
         );
     }
-    
+
     Ok(Buffers {
-        position: position_buffer,
-        color: color_buffer,
+        vertices: vertex_buffer,
         indices: index_buffer,
     })
-} 
\ No newline at end of file
+}